@@ -1,6 +1,7 @@
+use async_trait::async_trait;
 use ethers::{
     providers::{Provider, Http},
-    types::{U256},
+    types::{BlockNumber, U256},
     utils::{format_units},
     middleware::Middleware,
 };
@@ -42,26 +43,108 @@ pub async fn estimate_transfer_gas_fee() -> Result<String> {
     Ok(gas_fee_eth)
 }
 
-/// 更详细的版本，返回所有信息
-pub async fn get_gas_info() -> Result<GasInfo> {
-    let rpc_url = "https://arbitrum-sepolia-rpc.publicnode.com";
-    let provider = Provider::<Http>::try_from(rpc_url)
-        .context("Failed to create provider")?;
-    
-    // 获取 Gas 价格
-    let gas_price_wei = provider.get_gas_price().await?;
-    
+/// Gas 速度档位，对应常见 Gas 追踪服务的 safeLow/standard/fast/fastest 分类
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GasCategory {
+    /// 安全但较慢
+    Safe,
+    /// 标准速度
+    Standard,
+    /// 较快确认
+    Fast,
+    /// 最快确认
+    Fastest,
+}
+
+impl GasCategory {
+    /// 每个档位对应 `eth_feeHistory` 的矿工小费分位数
+    fn reward_percentile(self) -> f64 {
+        match self {
+            GasCategory::Safe => 25.0,
+            GasCategory::Standard => 50.0,
+            GasCategory::Fast => 75.0,
+            GasCategory::Fastest => 90.0,
+        }
+    }
+}
+
+/// Gas 价格来源的抽象，便于后续接入其他数据源（如第三方 Gas 追踪服务）
+#[async_trait]
+pub trait GasOracle: Send + Sync {
+    /// 返回指定速度档位下的 Gas 价格（wei）
+    async fn gas_price(&self, category: GasCategory) -> Result<U256>;
+}
+
+/// 基于 `eth_feeHistory` 的 Gas 价格预言机
+pub struct FeeHistoryGasOracle {
+    provider: Provider<Http>,
+}
+
+impl FeeHistoryGasOracle {
+    pub fn new(provider: Provider<Http>) -> Self {
+        Self { provider }
+    }
+
+    /// 连接 Arbitrum Sepolia 测试网构造预言机
+    pub fn connect_arbitrum_sepolia() -> Result<Self> {
+        let rpc_url = "https://arbitrum-sepolia-rpc.publicnode.com";
+        let provider = Provider::<Http>::try_from(rpc_url)
+            .context("Failed to create provider")?;
+
+        Ok(Self::new(provider))
+    }
+}
+
+#[async_trait]
+impl GasOracle for FeeHistoryGasOracle {
+    async fn gas_price(&self, category: GasCategory) -> Result<U256> {
+        // 最近 20 个区块的费用历史
+        let fee_history = self
+            .provider
+            .fee_history(U256::from(20u64), BlockNumber::Pending, &[category.reward_percentile()])
+            .await
+            .context("获取 eth_feeHistory 失败")?;
+
+        let rewards: Vec<U256> = fee_history
+            .reward
+            .iter()
+            .filter_map(|block_rewards| block_rewards.first().copied())
+            .collect();
+
+        // 节点未返回小费数据时，回退到单一 Gas 价格
+        if rewards.is_empty() {
+            return self.provider.get_gas_price().await.context("获取 Gas 价格失败");
+        }
+
+        let reward_sum = rewards.iter().fold(U256::zero(), |acc, reward| acc + reward);
+        let tip = reward_sum / U256::from(rewards.len());
+
+        let latest_base_fee = *fee_history
+            .base_fee_per_gas
+            .last()
+            .context("eth_feeHistory 未返回 baseFeePerGas")?;
+
+        Ok(latest_base_fee + tip)
+    }
+}
+
+/// 更详细的版本，返回指定速度档位下的完整 Gas 信息
+pub async fn get_gas_info(oracle: &dyn GasOracle, category: GasCategory) -> Result<GasInfo> {
+    // 获取该档位的 Gas 价格
+    let gas_price_wei = oracle.gas_price(category).await?;
+
     // 转换为不同的单位以便显示
     let gas_price_gwei = format_units(gas_price_wei, "gwei")?;
-    
+
     // 基础 Gas 限额
     let base_gas_limit = U256::from(21000);
-    
+
     // 计算预估费用
     let estimated_fee_wei = gas_price_wei * base_gas_limit;
     let estimated_fee_eth = format_units(estimated_fee_wei, "ether")?;
-    
+
     Ok(GasInfo {
+        category,
         gas_price_wei,
         gas_price_gwei,
         base_gas_limit,
@@ -73,6 +156,8 @@ pub async fn get_gas_info() -> Result<GasInfo> {
 /// 包含实时 Gas 信息的数据结构
 #[derive(Debug, Clone)]
 pub struct GasInfo {
+    /// 查询时使用的速度档位
+    pub category: GasCategory,
     /// Gas 价格（wei）
     pub gas_price_wei: U256,
     /// Gas 价格（gwei）
@@ -89,9 +174,11 @@ impl GasInfo {
     /// 格式化显示所有信息
     pub fn display(&self) -> String {
         format!(
-            "Gas 价格: {} gwei ({} wei)\n\
+            "档位: {:?}\n\
+             Gas 价格: {} gwei ({} wei)\n\
              基础 Gas 限额: {}\n\
              预估转账费用: {} ETH ({} wei)",
+            self.category,
             self.gas_price_gwei,
             self.gas_price_wei,
             self.base_gas_limit,
@@ -105,15 +192,23 @@ impl GasInfo {
 #[tokio::main]
 async fn main() -> Result<()> {
     println!("=== Arbitrum Sepolia 测试网 Gas 费估算 ===\n");
-    
-    // 1. 获取基础 Gas 信息
-    match get_gas_info().await {
-        Ok(gas_info) => {
-            println!("当前网络 Gas 信息:");
-            println!("{}", gas_info.display());
-            println!();
+
+    let oracle = FeeHistoryGasOracle::connect_arbitrum_sepolia()?;
+
+    // 1. 依次展示四个速度档位下的 Gas 信息
+    for category in [
+        GasCategory::Safe,
+        GasCategory::Standard,
+        GasCategory::Fast,
+        GasCategory::Fastest,
+    ] {
+        match get_gas_info(&oracle, category).await {
+            Ok(gas_info) => {
+                println!("{}", gas_info.display());
+                println!();
+            }
+            Err(e) => eprintln!("获取 Gas 信息失败（{:?}）: {}", category, e),
         }
-        Err(e) => eprintln!("获取 Gas 信息失败: {}", e),
     }
     Ok(())
 }
\ No newline at end of file
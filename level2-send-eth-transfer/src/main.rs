@@ -1,14 +1,21 @@
+mod token;
+
 use ethers::{
-    providers::{Provider, Http},
-    signers::{LocalWallet, Signer},
-    types::{Address, TransactionRequest,U64, U256, H256},
+    abi::{decode, ParamType},
+    providers::{Provider, Http, ProviderError, RpcError},
+    signers::{coins_bip39::English, LocalWallet, MnemonicBuilder, Signer},
+    types::{Address, BlockNumber, Bytes, Eip1559TransactionRequest, TransactionRequest,TransactionReceipt,U64, U256, H256},
     utils::{format_units,parse_units},
-    middleware::{Middleware,SignerMiddleware},
+    middleware::{
+        nonce_manager::NonceManagerMiddleware,
+        Middleware,SignerMiddleware,
+    },
 };
 use eyre::{Result, Context};
 use std::str::FromStr;
 use std::env;
 use std::sync::Arc;
+use std::time::Duration;
 use dotenv::dotenv;
 use ethers::types::transaction::eip2718::TypedTransaction;
 
@@ -33,27 +40,69 @@ pub fn get_arbitrum_sepolia_provider() -> Result<Provider<Http>> {
     
     Ok(provider)
 }
+/// 带 nonce 管理能力的转账客户端类型
+///
+/// 卡住交易的自动提价重发由 [`send_with_gas_escalation`] 在应用层实现，
+/// 而不是套一层 ethers 自带的 `GasEscalatorMiddleware`：该中间件只会监控
+/// 并重发 legacy `gas_price` 交易，对本项目在 Arbitrum Sepolia 上
+/// 几乎总是采用的 EIP-1559 交易完全不生效，套上去只是个摆设。
+pub type TransferClient = SignerMiddleware<NonceManagerMiddleware<Provider<Http>>, LocalWallet>;
+
 /// 创建带签名者的客户端
-pub fn create_signer_client(
-    provider: Provider<Http>,
-    wallet: LocalWallet,
-) -> SignerMiddleware<Provider<Http>, LocalWallet> {
+///
+/// `NonceManagerMiddleware` 在本地维护下一个可用 nonce，避免连续发送
+/// 多笔交易时因并发查询链上 nonce 而产生竞争；首次发送交易时不应手动
+/// 设置 nonce，否则会绕过它的本地计数。
+pub fn create_signer_client(provider: Provider<Http>, wallet: LocalWallet) -> TransferClient {
+    let address = wallet.address();
+    let provider = NonceManagerMiddleware::new(provider, address);
+
     SignerMiddleware::new(provider, wallet)
 }
 
 /// 从环境变量加载钱包
+///
+/// 优先读取 `PRIVATE_KEY`；未设置时回退到 `MNEMONIC`（默认派生账户索引 0），
+/// 方便只保留一份助记词来管理多个子账户。
 pub fn load_wallet_from_env() -> Result<LocalWallet> {
     dotenv().ok(); // 加载 .env 文件
-    
-    let private_key = env::var("PRIVATE_KEY")
-        .context("请在 .env 文件中设置 PRIVATE_KEY 环境变量")?;
-    
-    // 移除可能的 "0x" 前缀
-    let private_key = private_key.trim_start_matches("0x");
-    
-    let wallet = private_key.parse::<LocalWallet>()
-        .context("私钥格式无效")?;
-    
+
+    match env::var("PRIVATE_KEY") {
+        Ok(private_key) => {
+            // 移除可能的 "0x" 前缀
+            let private_key = private_key.trim_start_matches("0x");
+
+            private_key.parse::<LocalWallet>().context("私钥格式无效")
+        }
+        Err(_) => load_wallet_from_mnemonic(0)
+            .context("请在 .env 文件中设置 PRIVATE_KEY 或 MNEMONIC 环境变量"),
+    }
+}
+
+/// 默认的 BIP-44 以太坊派生路径模板，`{index}` 会被替换为账户序号
+const DEFAULT_DERIVATION_PATH: &str = "m/44'/60'/0'/0/{index}";
+
+/// 从 `MNEMONIC` 环境变量派生钱包
+///
+/// `index` 对应派生路径中的账户序号，可通过 `DERIVATION_PATH`（需包含
+/// `{index}` 占位符）自定义派生路径，从同一份助记词管理多个测试子账户。
+pub fn load_wallet_from_mnemonic(index: u32) -> Result<LocalWallet> {
+    dotenv().ok();
+
+    let mnemonic = env::var("MNEMONIC")
+        .context("请在 .env 文件中设置 MNEMONIC 环境变量")?;
+
+    let derivation_path = env::var("DERIVATION_PATH")
+        .unwrap_or_else(|_| DEFAULT_DERIVATION_PATH.to_string())
+        .replace("{index}", &index.to_string());
+
+    let wallet = MnemonicBuilder::<English>::default()
+        .phrase(mnemonic.as_str())
+        .derivation_path(&derivation_path)
+        .context("派生路径无效")?
+        .build()
+        .context("从助记词派生钱包失败")?;
+
     Ok(wallet)
 }
 
@@ -70,47 +119,275 @@ pub async fn get_balance_eth(address: Address) -> Result<String> {
     Ok(balance_eth)
 }
 
-/// 计算合适的 Gas 价格（添加 10% 溢价以确保快速确认）
-pub async fn get_gas_price_with_premium() -> Result<U256> {
+/// 获取当前 Gas 价格，不附加溢价
+///
+/// 用于链不支持 EIP-1559 时的 legacy 路径，以及 `eth_feeHistory` 未返回小费数据时的兜底；
+/// `eth_feeHistory` 调用本身出错时不会回退到这里。
+pub async fn get_gas_price() -> Result<U256> {
     let provider = get_arbitrum_sepolia_provider()?;
-    
-    let base_gas_price = provider.get_gas_price()
+
+    provider.get_gas_price()
         .await
-        .context("获取 Gas 价格失败")?;
-    
-    // 添加 10% 溢价
-    let premium = base_gas_price * 110 / 100;
-    
-    Ok(premium)
+        .context("获取 Gas 价格失败")
+}
+
+/// 最近参与费用估算的区块数量
+const FEE_HISTORY_BLOCK_COUNT: u64 = 20;
+/// 在最新 baseFee 基础上预留的涨幅，用于外推下一个区块的 baseFee
+const BASE_FEE_PROJECTION_PERCENT: u64 = 110;
+
+/// 基于 `eth_feeHistory` 估算 EIP-1559 费用参数
+///
+/// 取最近 `FEE_HISTORY_BLOCK_COUNT` 个区块、`percentile` 分位的矿工小费取平均，
+/// 作为 `maxPriorityFeePerGas`；以窗口内最新的 baseFee 按
+/// `BASE_FEE_PROJECTION_PERCENT` 外推下一个区块的 baseFee，
+/// 再乘以 2 留出连续几次涨价的余量，加上 `maxPriorityFeePerGas` 得到 `maxFeePerGas`。
+/// 若节点返回的 reward 数组为空（如历史数据不足），回退到 `get_gas_price`，两个字段都取该值；
+/// `eth_feeHistory` 调用本身失败时直接返回错误。
+pub async fn estimate_eip1559_fees(percentile: u8) -> Result<(U256, U256)> {
+    let provider = get_arbitrum_sepolia_provider()?;
+
+    let fee_history = provider
+        .fee_history(
+            U256::from(FEE_HISTORY_BLOCK_COUNT),
+            BlockNumber::Pending,
+            &[percentile as f64],
+        )
+        .await
+        .context("获取 eth_feeHistory 失败")?;
+
+    let rewards: Vec<U256> = fee_history
+        .reward
+        .iter()
+        .filter_map(|block_rewards| block_rewards.first().copied())
+        .collect();
+
+    if rewards.is_empty() {
+        let gas_price = get_gas_price().await?;
+        return Ok((gas_price, gas_price));
+    }
+
+    let reward_sum = rewards.iter().fold(U256::zero(), |acc, reward| acc + reward);
+    let max_priority_fee_per_gas = reward_sum / U256::from(rewards.len());
+
+    let latest_base_fee = *fee_history
+        .base_fee_per_gas
+        .last()
+        .context("eth_feeHistory 未返回 baseFeePerGas")?;
+    let projected_base_fee = latest_base_fee * BASE_FEE_PROJECTION_PERCENT / 100;
+
+    let max_fee_per_gas = projected_base_fee * 2 + max_priority_fee_per_gas;
+
+    Ok((max_fee_per_gas, max_priority_fee_per_gas))
+}
+
+/// 获取 EIP-1559 费用参数（maxFeePerGas / maxPriorityFeePerGas）
+///
+/// 返回 `None` 表示当前链的 pending 区块不带 `baseFeePerGas`（即不支持
+/// EIP-1559），调用方应回退到传统的 `gas_price` 方案。
+pub async fn get_eip1559_fees() -> Result<Option<(U256, U256)>> {
+    let provider = get_arbitrum_sepolia_provider()?;
+
+    let pending_block = provider
+        .get_block(BlockNumber::Pending)
+        .await
+        .context("获取 pending 区块失败")?;
+
+    if pending_block.and_then(|block| block.base_fee_per_gas).is_none() {
+        return Ok(None);
+    }
+
+    // 50 分位小费，由 eth_feeHistory 估算得出更贴近市场的费用
+    let fees = estimate_eip1559_fees(50).await?;
+    Ok(Some(fees))
 }
 
-/// 估算转账所需的 Gas 限额
+/// `Error(string)` 的函数选择器
+const REVERT_ERROR_SELECTOR: [u8; 4] = [0x08, 0xc3, 0x79, 0xa0];
+
+/// 尝试从 `eth_estimateGas` 返回的错误里解码 `Error(string)` revert 原因
+///
+/// 节点执行失败时通常把 ABI 编码后的 revert 数据放在 JSON-RPC 错误的
+/// `data` 字段里：4 字节选择器 `0x08c379a0` 后跟一个 ABI 编码的
+/// `string`。解不出来就返回 `None`，调用方回退到展示原始错误信息。
+fn decode_revert_reason(error: &ProviderError) -> Option<String> {
+    let data = error.as_error_response()?.data.as_ref()?;
+    let data_str = data.as_str()?;
+    let data_bytes = hex::decode(data_str.trim_start_matches("0x")).ok()?;
+
+    if data_bytes.len() < 4 || data_bytes[..4] != REVERT_ERROR_SELECTOR {
+        return None;
+    }
+
+    let decoded = decode(&[ParamType::String], &data_bytes[4..]).ok()?;
+    decoded.into_iter().next()?.into_string()
+}
+
+/// 估算转账（或可选带 `data` 的合约调用）所需的 Gas 限额
+///
+/// `data` 为 `None` 时视为纯 ETH 转账：估算失败但无法解出 revert 原因时，
+/// 回退到基础 Gas 限额 21000。带 `data` 的合约调用估算失败大概率意味着
+/// 交易必定失败，此时直接把（尽量解码过的）错误传播出去，
+/// 避免用户带着一个必然失败的 21000 限额广播交易。
 pub async fn estimate_gas_limit(
     from: Address,
     to: Address,
     value: U256,
+    data: Option<Bytes>,
 ) -> Result<U256> {
     let provider = get_arbitrum_sepolia_provider()?;
-    
+
     // 创建交易请求
-    let tx = TransactionRequest::new()
+    let mut tx = TransactionRequest::new()
         .from(from)
         .to(to)
         .value(value);
+    if let Some(data) = data.clone() {
+        tx = tx.data(data);
+    }
 
     let typed_tx: TypedTransaction = tx.into();
-    
+
     // 估算 Gas 限额
-    let gas_limit = provider.estimate_gas(&typed_tx, None)
-        .await
-        .unwrap_or_else(|_| U256::from(21000)); // 失败时使用基础值
-    
+    let gas_limit = match provider.estimate_gas(&typed_tx, None).await {
+        Ok(gas_limit) => gas_limit,
+        Err(err) => {
+            if let Some(reason) = decode_revert_reason(&err) {
+                return Err(eyre::eyre!("Gas 估算失败，交易将被回退: {}", reason));
+            }
+
+            if data.is_none() {
+                U256::from(21000) // 纯转账场景下，无法解码原因就使用基础值兜底
+            } else {
+                return Err(err).context("Gas 估算失败");
+            }
+        }
+    };
+
     // 添加 20% 缓冲
     let gas_limit_with_buffer = gas_limit * 120 / 100;
-    
+
     Ok(gas_limit_with_buffer)
 }
 
+/// 根据当前费用模型构建转账交易（不含 nonce，由调用方决定是否复用）
+fn build_transfer_tx(
+    to_address: Address,
+    amount_wei: U256,
+    gas_limit: U256,
+    eip1559_fees: Option<(U256, U256)>,
+    legacy_gas_price: Option<U256>,
+) -> TypedTransaction {
+    if let Some((max_fee_per_gas, max_priority_fee_per_gas)) = eip1559_fees {
+        Eip1559TransactionRequest::new()
+            .to(to_address)
+            .value(amount_wei)
+            .max_fee_per_gas(max_fee_per_gas)
+            .max_priority_fee_per_gas(max_priority_fee_per_gas)
+            .gas(gas_limit)
+            .into()
+    } else {
+        TransactionRequest::new()
+            .to(to_address)
+            .value(amount_wei)
+            .gas_price(legacy_gas_price.expect("legacy 路径下 Gas 价格必存在"))
+            .gas(gas_limit)
+            .into()
+    }
+}
+
+/// 单轮轮询之间的等待时长
+const ESCALATION_POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// 每轮轮询次数，超过仍未确认就判定为"卡住"并提价重发
+const ESCALATION_POLLS_PER_ROUND: u32 = 3;
+/// 最多重发次数（不含首次发送）
+const MAX_GAS_ESCALATIONS: u32 = 5;
+/// 每次重发时 Gas 价格在上一次基础上提高的比例（分子/分母）
+const GAS_ESCALATION_FACTOR_NUM: u64 = 1125;
+const GAS_ESCALATION_FACTOR_DEN: u64 = 1000;
+
+/// 发送交易，并在长时间未被打包时按同一 nonce、更高的 Gas 价格自动重发
+///
+/// ethers 自带的 `GasEscalatorMiddleware` 只理解 legacy 交易的单一
+/// `gasPrice` 字段，无法正确处理 EIP-1559 交易的
+/// `maxFeePerGas`/`maxPriorityFeePerGas`，因此这里在应用层自行实现：
+/// 交易发出后按固定间隔轮询收据，长时间未确认就把两个费用字段同比例
+/// 提高、复用原 nonce 重新签名广播，直到确认或达到最大重发次数。
+async fn send_with_gas_escalation(
+    client: &TransferClient,
+    to_address: Address,
+    amount_wei: U256,
+    gas_limit: U256,
+    mut eip1559_fees: Option<(U256, U256)>,
+    mut legacy_gas_price: Option<U256>,
+) -> Result<(H256, Option<TransactionReceipt>)> {
+    let mut typed_tx = build_transfer_tx(to_address, amount_wei, gas_limit, eip1559_fees, legacy_gas_price);
+
+    let pending_tx = client
+        .send_transaction(typed_tx.clone(), None)
+        .await
+        .context("发送交易失败")?;
+    let mut tx_hash = pending_tx.tx_hash();
+    println!("✓ 交易已发送！交易哈希: {:?}", tx_hash);
+
+    // 记录本次实际使用的 nonce，供后续重发复用（避免另起炉灶产生新 nonce）
+    let nonce = client
+        .get_transaction(tx_hash)
+        .await
+        .context("查询交易详情失败")?
+        .context("未找到刚发送的交易")?
+        .nonce;
+
+    for attempt in 1..=MAX_GAS_ESCALATIONS {
+        let mut receipt = None;
+        for _ in 0..ESCALATION_POLLS_PER_ROUND {
+            tokio::time::sleep(ESCALATION_POLL_INTERVAL).await;
+            receipt = client
+                .get_transaction_receipt(tx_hash)
+                .await
+                .context("查询交易收据失败")?;
+            if receipt.is_some() {
+                break;
+            }
+        }
+
+        if receipt.is_some() {
+            return Ok((tx_hash, receipt));
+        }
+
+        // 仍未确认：同比例提高 Gas 价格，复用同一 nonce 重发
+        eip1559_fees = eip1559_fees.map(|(max_fee, max_priority)| {
+            (
+                max_fee * GAS_ESCALATION_FACTOR_NUM / GAS_ESCALATION_FACTOR_DEN,
+                max_priority * GAS_ESCALATION_FACTOR_NUM / GAS_ESCALATION_FACTOR_DEN,
+            )
+        });
+        legacy_gas_price = legacy_gas_price
+            .map(|gas_price| gas_price * GAS_ESCALATION_FACTOR_NUM / GAS_ESCALATION_FACTOR_DEN);
+
+        typed_tx = build_transfer_tx(to_address, amount_wei, gas_limit, eip1559_fees, legacy_gas_price);
+        typed_tx.set_nonce(nonce);
+
+        println!(
+            "⏳ 第 {} 次尝试仍未确认，提高 Gas 价格后重发（nonce 不变）...",
+            attempt
+        );
+        let pending_tx = client
+            .send_transaction(typed_tx.clone(), None)
+            .await
+            .context("重发交易失败")?;
+        tx_hash = pending_tx.tx_hash();
+        println!("✓ 重发交易已发送！交易哈希: {:?}", tx_hash);
+    }
+
+    // 达到最大重发次数：不再重发，最后再查一次收据
+    let receipt = client
+        .get_transaction_receipt(tx_hash)
+        .await
+        .context("查询交易收据失败")?;
+    Ok((tx_hash, receipt))
+}
+
 /// 发送 ETH 转账
 pub async fn send_eth_transfer(
     from_wallet: LocalWallet,
@@ -121,46 +398,55 @@ pub async fn send_eth_transfer(
     
   // 2. 设置链 ID（Arbitrum Sepolia = 421614）
     let wallet = from_wallet.clone().with_chain_id(421614u64);
-    // 创建带签名者的客户端
-    let client = Arc::new(SignerMiddleware::new(
-        provider.clone(),
-        wallet
-    ));
+    // 创建带 nonce 管理能力的签名客户端
+    let client = Arc::new(create_signer_client(provider.clone(), wallet));
+    let address = client.address();
 
     // 使用 ethers 官方工具解析金额
     let parsed_amount = parse_units(amount_eth, "ether").context("金额格式无效")?;
     let amount_wei: U256 = parsed_amount.into();
 
-    // 获取 nonce
-    let nonce = client.get_transaction_count(client.address(), None)
-        .await
-        .context("获取 nonce 失败")?;
-    
-    // 获取 Gas 价格
-    let gas_price = get_gas_price_with_premium().await?;
-    
+    // nonce 不在此处查询：实际发送时交给 NonceManagerMiddleware 本地填充，
+    // 避免手动查询/设置与其内部计数产生竞争
+
+    // 优先尝试 EIP-1559 费用模型，链不支持时回退到传统 gas_price
+    let eip1559_fees = get_eip1559_fees().await?;
+    let legacy_gas_price = match eip1559_fees {
+        Some(_) => None,
+        None => Some(get_gas_price().await?),
+    };
+
     // 估算 Gas 限额
     let gas_limit = estimate_gas_limit(
-        client.address(),
+        address,
         to_address,
         amount_wei,
+        None,
     ).await?;
-    
+
     println!("交易参数:");
-    println!("• From: {:?}", client.address());
+    println!("• From: {:?}", address);
     println!("• To: {:?}", to_address);
     println!("• 金额: {} ETH", amount_eth);
-    println!("• Nonce: {}", nonce);
-    println!("• Gas 价格: {} wei", gas_price);
+    if let Some((max_fee_per_gas, max_priority_fee_per_gas)) = eip1559_fees {
+        println!("• maxFeePerGas: {} wei", max_fee_per_gas);
+        println!("• maxPriorityFeePerGas: {} wei", max_priority_fee_per_gas);
+    } else if let Some(gas_price) = legacy_gas_price {
+        println!("• Gas 价格（legacy）: {} wei", gas_price);
+    }
     println!("• Gas 限额: {}", gas_limit);
-    
-    // 计算预估 Gas 费
-    let estimated_fee = gas_price * gas_limit;
+
+    // 计算预估 Gas 费（EIP-1559 下按 maxFeePerGas 上限估算）
+    let fee_cap = eip1559_fees
+        .map(|(max_fee_per_gas, _)| max_fee_per_gas)
+        .or(legacy_gas_price)
+        .expect("EIP-1559 费用或 legacy Gas 价格必有其一");
+    let estimated_fee = fee_cap * gas_limit;
     let estimated_fee_eth = format_units(estimated_fee, "ether")?;
     println!("• 预估 Gas 费: {} ETH", estimated_fee_eth);
     
     // 检查余额是否足够
-    let balance = client.get_balance(client.address(), None).await?;
+    let balance = client.get_balance(address, None).await?;
     let total_cost = amount_wei + estimated_fee;
     
     if balance < total_cost {
@@ -174,28 +460,18 @@ pub async fn send_eth_transfer(
         ));
     }
     
-    // 构建并发送交易
+    // 构建并发送交易，长时间未确认时自动提价重发（同一 nonce）
     println!("\n正在发送交易...");
-    
-    let tx = TransactionRequest::new()
-        .to(to_address)
-        .value(amount_wei)
-        .gas_price(gas_price)
-        .gas(gas_limit)
-        .nonce(nonce);
-    
-    let pending_tx = client.send_transaction(tx, None).await
-        .context("发送交易失败")?;
-    
-    let tx_hash = pending_tx.tx_hash();
-    println!("✓ 交易已发送！交易哈希: {:?}", tx_hash);
-    
-    // 等待交易确认
-    println!("等待交易确认...");
-    let receipt = pending_tx
-        .await
-        .context("等待交易确认失败")?;
-    
+
+    let (tx_hash, receipt) = send_with_gas_escalation(
+        client.as_ref(),
+        to_address,
+        amount_wei,
+        gas_limit,
+        eip1559_fees,
+        legacy_gas_price,
+    ).await?;
+
     match receipt {
         Some(receipt) => {
             println!("✓ 交易已确认！");
@@ -305,9 +581,43 @@ async fn main() -> Result<()> {
     println!("\n6. 转账后余额检查...");
     let new_sender_balance = get_balance_eth(from_wallet.address()).await?;
     let new_receiver_balance = get_balance_eth(to_address).await?;
-    
+
     println!("  发送方新余额: {} ETH", new_sender_balance);
     println!("  接收方新余额: {} ETH", new_receiver_balance);
-    
+
+    // 7. 可选：设置 TOKEN_ADDRESS 后演示 ERC20 代币余额查询 / 转账
+    if let Ok(token_address_str) = env::var("TOKEN_ADDRESS") {
+        println!("\n7. ERC20 代币操作...");
+
+        let token_address = match validate_address(&token_address_str) {
+            Ok(addr) => addr,
+            Err(e) => {
+                eprintln!("  TOKEN_ADDRESS 无效: {}", e);
+                return Ok(());
+            }
+        };
+
+        let read_provider = get_arbitrum_sepolia_provider()?;
+        let token = token::erc20_contract(token_address, read_provider);
+
+        match token::get_token_balance(&token, from_wallet.address()).await {
+            Ok(balance) => println!("  代币余额: {}", balance),
+            Err(e) => eprintln!("  查询代币余额失败: {}", e),
+        }
+
+        // 额外设置 TOKEN_TRANSFER_AMOUNT 后才真正发起代币转账，避免误转
+        if let Ok(transfer_amount) = env::var("TOKEN_TRANSFER_AMOUNT") {
+            let wallet = from_wallet.clone().with_chain_id(421614u64);
+            let provider = get_arbitrum_sepolia_provider()?;
+            let client = Arc::new(create_signer_client(provider, wallet));
+            let token_with_signer = token::erc20_contract_with_signer(token_address, client);
+
+            match token::send_token_transfer(&token_with_signer, to_address, &transfer_amount).await {
+                Ok(tx_hash) => println!("  代币转账已发送，交易哈希: {:?}", tx_hash),
+                Err(e) => eprintln!("  代币转账失败: {}", e),
+            }
+        }
+    }
+
     Ok(())
 }
\ No newline at end of file
@@ -0,0 +1,101 @@
+use crate::TransferClient;
+use ethers::{
+    contract::abigen,
+    providers::{Http, Provider},
+    types::{Address, TxHash, U256},
+    utils::{format_units, parse_units},
+};
+use eyre::{Context, Result};
+use std::sync::Arc;
+
+abigen!(
+    Erc20,
+    r#"[
+        function balanceOf(address account) external view returns (uint256)
+        function transfer(address to, uint256 amount) external returns (bool)
+        function approve(address spender, uint256 amount) external returns (bool)
+        function allowance(address owner, address spender) external view returns (uint256)
+        function decimals() external view returns (uint8)
+    ]"#,
+);
+
+/// 只读 ERC20 客户端，用于 balanceOf / allowance / decimals 这类查询
+pub fn erc20_contract(token_address: Address, provider: Provider<Http>) -> Erc20<Provider<Http>> {
+    Erc20::new(token_address, Arc::new(provider))
+}
+
+/// 带签名者的 ERC20 客户端，用于 transfer / approve 这类写操作
+pub fn erc20_contract_with_signer(
+    token_address: Address,
+    client: Arc<TransferClient>,
+) -> Erc20<TransferClient> {
+    Erc20::new(token_address, client)
+}
+
+/// 查询账户的代币余额，并按代币自身的 `decimals()` 格式化，而非写死 18 位
+pub async fn get_token_balance(contract: &Erc20<Provider<Http>>, account: Address) -> Result<String> {
+    let balance = contract
+        .balance_of(account)
+        .call()
+        .await
+        .context("查询代币余额失败")?;
+    let decimals = contract.decimals().call().await.context("查询代币精度失败")?;
+
+    format_units(balance, decimals as u32).context("格式化代币余额失败")
+}
+
+/// 查询 `owner` 授予 `spender` 的代币额度，同样按代币精度格式化
+pub async fn get_token_allowance(
+    contract: &Erc20<Provider<Http>>,
+    owner: Address,
+    spender: Address,
+) -> Result<String> {
+    let allowance = contract
+        .allowance(owner, spender)
+        .call()
+        .await
+        .context("查询授权额度失败")?;
+    let decimals = contract.decimals().call().await.context("查询代币精度失败")?;
+
+    format_units(allowance, decimals as u32).context("格式化授权额度失败")
+}
+
+/// 发起代币转账，`amount` 为人类可读金额（如 "1.5"），按代币精度换算为最小单位
+pub async fn send_token_transfer(
+    contract: &Erc20<TransferClient>,
+    to: Address,
+    amount: &str,
+) -> Result<TxHash> {
+    let decimals = contract.decimals().call().await.context("查询代币精度失败")?;
+    let amount_raw: U256 = parse_units(amount, decimals as u32)
+        .context("金额格式无效")?
+        .into();
+
+    let pending_tx = contract
+        .transfer(to, amount_raw)
+        .send()
+        .await
+        .context("发送代币转账失败")?;
+
+    Ok(pending_tx.tx_hash())
+}
+
+/// 授权 `spender` 可转移的代币额度，`amount` 同样为人类可读金额
+pub async fn approve_token_spender(
+    contract: &Erc20<TransferClient>,
+    spender: Address,
+    amount: &str,
+) -> Result<TxHash> {
+    let decimals = contract.decimals().call().await.context("查询代币精度失败")?;
+    let amount_raw: U256 = parse_units(amount, decimals as u32)
+        .context("金额格式无效")?
+        .into();
+
+    let pending_tx = contract
+        .approve(spender, amount_raw)
+        .send()
+        .await
+        .context("发送代币授权失败")?;
+
+    Ok(pending_tx.tx_hash())
+}